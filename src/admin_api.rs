@@ -0,0 +1,167 @@
+use crate::{
+    error::{Error, Result},
+    settings::Settings,
+};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use slog::{info, o, Logger};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct Counters {
+    uplinks_received: AtomicU64,
+    uplinks_routed: AtomicU64,
+    uplinks_routed_per_oui: RwLock<HashMap<u32, u64>>,
+    downlinks_rx1: AtomicU64,
+    downlinks_rx2: AtomicU64,
+}
+
+impl Counters {
+    pub fn record_uplink_received(&self) {
+        self.uplinks_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_uplink_routed(&self, oui: Option<u32>) {
+        self.uplinks_routed.fetch_add(1, Ordering::Relaxed);
+        if let Some(oui) = oui {
+            let mut per_oui = self.uplinks_routed_per_oui.write().unwrap();
+            *per_oui.entry(oui).or_insert(0) += 1;
+        }
+    }
+
+    pub fn record_downlink_rx1(&self) {
+        self.downlinks_rx1.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_downlink_rx2(&self) {
+        self.downlinks_rx2.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub type Metrics = Arc<Counters>;
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct RouterClientInfo {
+    pub oui: u32,
+    pub eui_filter_count: usize,
+    pub subnet_count: usize,
+    pub router_uris: Vec<String>,
+}
+
+/// Snapshot of `Router`'s routing tables, republished on every routing update.
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct RoutingSnapshot {
+    pub routing_height: u64,
+    pub clients: Vec<RouterClientInfo>,
+    pub default_router_uris: Vec<String>,
+}
+
+pub type SharedRoutingSnapshot = Arc<RwLock<RoutingSnapshot>>;
+
+#[derive(Serialize)]
+struct StatusResponse {
+    routing_height: u64,
+    clients: Vec<RouterClientInfo>,
+    default_clients: Vec<String>,
+    uplinks_received: u64,
+    uplinks_routed: u64,
+    uplinks_routed_per_oui: HashMap<u32, u64>,
+    downlinks_rx1: u64,
+    downlinks_rx2: u64,
+}
+
+/// An optional HTTP admin/status API.
+pub struct AdminApi {
+    listen_addr: SocketAddr,
+    metrics: Metrics,
+    routing: SharedRoutingSnapshot,
+}
+
+impl AdminApi {
+    pub fn new(
+        settings: &Settings,
+        metrics: Metrics,
+        routing: SharedRoutingSnapshot,
+    ) -> Result<Self> {
+        Ok(Self {
+            listen_addr: settings.admin_listen_addr,
+            metrics,
+            routing,
+        })
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "admin_api"));
+        info!(logger, "starting admin api on {}", self.listen_addr);
+        let metrics = self.metrics.clone();
+        let routing = self.routing.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let metrics = metrics.clone();
+            let routing = routing.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    handle_request(req, metrics.clone(), routing.clone())
+                }))
+            }
+        });
+        let server = Server::try_bind(&self.listen_addr)
+            .map_err(|err| Error::ServerError(err.to_string()))?
+            .serve(make_svc);
+        server
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(|err| Error::ServerError(err.to_string()))?;
+        info!(logger, "stopped");
+        Ok(())
+    }
+}
+
+async fn handle_request(
+    req: Request<Body>,
+    metrics: Metrics,
+    routing: SharedRoutingSnapshot,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => status_response(&metrics, &routing),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    };
+    Ok(response)
+}
+
+fn status_response(metrics: &Metrics, routing: &SharedRoutingSnapshot) -> Response<Body> {
+    let snapshot = routing.read().unwrap();
+    let body = StatusResponse {
+        routing_height: snapshot.routing_height,
+        clients: snapshot.clients.clone(),
+        default_clients: snapshot.default_router_uris.clone(),
+        uplinks_received: metrics.uplinks_received.load(Ordering::Relaxed),
+        uplinks_routed: metrics.uplinks_routed.load(Ordering::Relaxed),
+        uplinks_routed_per_oui: metrics.uplinks_routed_per_oui.read().unwrap().clone(),
+        downlinks_rx1: metrics.downlinks_rx1.load(Ordering::Relaxed),
+        downlinks_rx2: metrics.downlinks_rx2.load(Ordering::Relaxed),
+    };
+    match serde_json::to_vec(&body) {
+        Ok(json) => Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(json))
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
@@ -27,6 +27,8 @@ pub enum Error {
     ServiceError(#[from] helium_proto::services::Error),
     #[error("rpc error")]
     RpcError(#[from] tonic::Status),
+    #[error("tls error")]
+    TlsError(#[from] tonic::transport::Error),
     #[error("protobuf error")]
     ProstError(#[from] prost::EncodeError),
 }
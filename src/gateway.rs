@@ -1,4 +1,4 @@
-use crate::{error::Result, link_packet::LinkPacket, settings::Settings};
+use crate::{admin_api::Metrics, error::Result, link_packet::LinkPacket, settings::Settings};
 use semtech_udp::{
     server_runtime::{Error as SemtechError, Event, UdpRuntime},
     tx_ack,
@@ -15,6 +15,7 @@ pub struct Gateway {
     uplinks: Sender<LinkPacket>,
     downlinks: Receiver<LinkPacket>,
     udp_runtime: UdpRuntime,
+    metrics: Metrics,
 }
 
 impl Gateway {
@@ -22,11 +23,13 @@ impl Gateway {
         uplinks: Sender<LinkPacket>,
         downlinks: Receiver<LinkPacket>,
         settings: &Settings,
+        metrics: Metrics,
     ) -> Result<Self> {
         let gateway = Gateway {
             uplinks,
             downlinks,
             udp_runtime: UdpRuntime::new(settings.listen_addr).await?,
+            metrics,
         };
         Ok(gateway)
     }
@@ -111,23 +114,27 @@ impl Gateway {
                         .dispatch(Some(Duration::from_secs(DOWNLINK_TIMEOUT_SECS)))
                         .await
                     {
-                        Err(SemtechError::AckError(tx_ack::Error::NONE)) => Ok(()),
+                        Err(SemtechError::AckError(tx_ack::Error::NONE)) | Ok(()) => {
+                            self.metrics.record_downlink_rx2();
+                            Ok(())
+                        }
                         Err(err) => {
                             debug!(logger, "ignoring rx2 downlink error: {:?}", err);
                             Ok(())
                         }
-                        Ok(()) => Ok(()),
                     }
                 } else {
                     Ok(())
                 }
             }
-            Err(SemtechError::AckError(tx_ack::Error::NONE)) => Ok(()),
+            Err(SemtechError::AckError(tx_ack::Error::NONE)) | Ok(()) => {
+                self.metrics.record_downlink_rx1();
+                Ok(())
+            }
             Err(err) => {
                 debug!(logger, "ignoring rx1 downlink error: {:?}", err);
                 Ok(())
             }
-            Ok(()) => Ok(()),
         }
     }
 }
@@ -0,0 +1,128 @@
+use crate::{error::Error, error::Result, link_packet::LinkPacket, settings::Settings};
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS};
+use slog::{debug, info, o, warn, Logger};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// Keep-alive ping interval for the broker connection.
+pub const MQTT_KEEPALIVE_SECS: u64 = 30;
+/// Depth of the client's internal request queue.
+pub const MQTT_CAP: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct MqttSettings {
+    pub uri: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub uplink_qos: QoS,
+    pub downlink_qos: QoS,
+    /// Topic template with a `{mac}` placeholder, e.g. `gateway/{mac}/uplink`.
+    pub uplink_topic: String,
+    /// Topic template with a `{mac}` placeholder, e.g. `gateway/{mac}/downlink`.
+    pub downlink_topic: String,
+}
+
+#[derive(Debug)]
+pub struct MqttGateway {
+    uplinks: Sender<LinkPacket>,
+    downlinks: Receiver<LinkPacket>,
+    client: AsyncClient,
+    eventloop: rumqttc::EventLoop,
+    downlink_topic: String,
+    downlink_qos: QoS,
+}
+
+impl MqttGateway {
+    pub async fn new(
+        uplinks: Sender<LinkPacket>,
+        downlinks: Receiver<LinkPacket>,
+        settings: &Settings,
+        gateway_mac: &str,
+    ) -> Result<Self> {
+        let mqtt_settings = settings
+            .mqtt
+            .as_ref()
+            .ok_or_else(|| Error::ServerError("missing mqtt settings".to_string()))?;
+        let uri: http::Uri = mqtt_settings.uri.parse()?;
+        let host = uri.host().unwrap_or("localhost").to_string();
+        let port = uri.port_u16().unwrap_or(1883);
+        let mut mqtt_options = MqttOptions::new(format!("gateway-rs-{}", gateway_mac), host, port);
+        mqtt_options.set_keep_alive(std::time::Duration::from_secs(MQTT_KEEPALIVE_SECS));
+        if let (Some(username), Some(password)) =
+            (&mqtt_settings.username, &mqtt_settings.password)
+        {
+            mqtt_options.set_credentials(username, password);
+        }
+        let (client, eventloop) = AsyncClient::new(mqtt_options, MQTT_CAP);
+        let uplink_topic = mqtt_settings.uplink_topic.replace("{mac}", gateway_mac);
+        client
+            .subscribe(uplink_topic, mqtt_settings.uplink_qos)
+            .await
+            .map_err(|err| Error::ServerError(err.to_string()))?;
+        Ok(Self {
+            uplinks,
+            downlinks,
+            client,
+            eventloop,
+            downlink_topic: mqtt_settings.downlink_topic.replace("{mac}", gateway_mac),
+            downlink_qos: mqtt_settings.downlink_qos,
+        })
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "mqtt_gateway"));
+        info!(logger, "starting mqtt gateway");
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                event = self.eventloop.poll() => self.handle_mqtt_event(logger.clone(), event).await?,
+                downlink = self.downlinks.recv() => match downlink {
+                    Some(packet) => self.handle_downlink(logger.clone(), packet).await?,
+                    None => {
+                        debug!(logger, "ignoring closed downlinks channel");
+                        continue;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_mqtt_event(
+        &mut self,
+        logger: Logger,
+        event: std::result::Result<MqttEvent, rumqttc::ConnectionError>,
+    ) -> Result {
+        match event {
+            Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                match serde_json::from_slice::<LinkPacket>(&publish.payload) {
+                    Ok(packet) => {
+                        let _ = self.uplinks.send(packet).await;
+                    }
+                    Err(err) => {
+                        debug!(logger, "ignoring unparseable mqtt uplink frame: {:?}", err);
+                    }
+                }
+            }
+            Ok(_) => (),
+            Err(err) => {
+                warn!(logger, "mqtt connection error: {:?}", err);
+            }
+        };
+        Ok(())
+    }
+
+    async fn handle_downlink(&mut self, logger: Logger, downlink: LinkPacket) -> Result {
+        let payload = serde_json::to_vec(&downlink)?;
+        match self
+            .client
+            .publish(&self.downlink_topic, self.downlink_qos, false, payload)
+            .await
+        {
+            Ok(()) => (),
+            Err(err) => debug!(logger, "ignoring mqtt downlink publish error: {:?}", err),
+        }
+        Ok(())
+    }
+}
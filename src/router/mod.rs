@@ -1,4 +1,11 @@
-use crate::{error::Result, keypair, link_packet::LinkPacket, settings::Settings};
+use crate::{
+    admin_api::{Metrics, RouterClientInfo, RoutingSnapshot, SharedRoutingSnapshot},
+    error::Result,
+    keypair,
+    link_packet::LinkPacket,
+    settings::Settings,
+    store_and_forward::{RouteTarget, SharedForwardQueue},
+};
 use helium_proto::{
     services::{self, Channel, Endpoint},
     BlockchainStateChannelMessageV1, RoutingInformation, RoutingRequest, RoutingResponse,
@@ -11,6 +18,9 @@ use tokio::sync::mpsc::{Receiver, Sender};
 
 pub mod filter;
 pub mod routing;
+pub mod tls;
+
+use tls::TlsSettings;
 
 pub const CONNECT_TIMEOUT: u64 = 10;
 
@@ -25,29 +35,49 @@ pub use helium_proto::Region;
 pub type RouterClient = services::router::Client<Channel>;
 pub type ValidatorClient = services::validator::Client<Channel>;
 
-pub fn mk_router_client(uri: Uri) -> Result<RouterClient> {
-    let channel = Endpoint::from(uri)
-        .timeout(Duration::from_secs(CONNECT_TIMEOUT))
-        .connect_lazy()?;
+pub fn mk_router_client(uri: Uri, tls: Option<&TlsSettings>) -> Result<RouterClient> {
+    let channel = mk_endpoint(uri, tls)?.connect_lazy()?;
     Ok(RouterClient::new(channel))
 }
 
-pub fn mk_validator_client(uri: Uri) -> Result<ValidatorClient> {
-    let channel = Endpoint::from(uri)
-        .timeout(Duration::from_secs(CONNECT_TIMEOUT))
-        .connect_lazy()?;
+pub fn mk_validator_client(uri: Uri, tls: Option<&TlsSettings>) -> Result<ValidatorClient> {
+    let channel = mk_endpoint(uri, tls)?.connect_lazy()?;
     Ok(ValidatorClient::new(channel))
 }
 
+/// Build an `Endpoint` for `uri`, wiring in TLS when the scheme calls for it
+/// (`https`/`grpcs`) or a `tls` settings block is present; plaintext otherwise.
+fn mk_endpoint(uri: Uri, tls: Option<&TlsSettings>) -> Result<Endpoint> {
+    let mut endpoint = Endpoint::from(uri.clone()).timeout(Duration::from_secs(CONNECT_TIMEOUT));
+    if tls::uses_tls(&uri) || tls.is_some() {
+        let tls_config = tls::client_tls_config(&uri, tls)?;
+        endpoint = endpoint.tls_config(tls_config)?;
+    }
+    Ok(endpoint)
+}
+
+/// Initial delay before the first validator reconnect attempt.
+pub const RECONNECT_BACKOFF_INITIAL_SECS: u64 = 1;
+/// Reconnect backoff is capped here so a long outage still retries regularly.
+pub const RECONNECT_BACKOFF_MAX_SECS: u64 = 60;
+/// How often queued uplinks are retried against the current routing tables.
+pub const QUEUE_DRAIN_INTERVAL_SECS: u64 = 30;
+
 pub struct Router {
     downlinks: Sender<LinkPacket>,
     uplinks: Receiver<LinkPacket>,
     keypair: Arc<keypair::Keypair>,
     region: Region,
     validator: ValidatorClient,
+    validator_uri: Uri,
     routing_height: u64,
     clients: HashMap<u32, routing::Routing>,
     default_clients: Vec<RouterClient>,
+    default_router_uris: Vec<String>,
+    tls: Option<TlsSettings>,
+    metrics: Metrics,
+    routing_snapshot: SharedRoutingSnapshot,
+    queue: SharedForwardQueue,
 }
 
 impl Router {
@@ -55,44 +85,72 @@ impl Router {
         downlinks: Sender<LinkPacket>,
         uplinks: Receiver<LinkPacket>,
         settings: &Settings,
+        metrics: Metrics,
+        routing_snapshot: SharedRoutingSnapshot,
+        queue: SharedForwardQueue,
     ) -> Result<Self> {
-        let validator = mk_validator_client(settings.validator.clone())?;
+        let tls = settings.tls.clone();
+        let validator_uri = settings.validator.clone();
+        let validator = mk_validator_client(validator_uri.clone(), tls.as_ref())?;
         let default_clients: Vec<RouterClient> = settings
             .routers
             .iter()
-            .map(|uri| mk_router_client(uri.clone()))
+            .map(|uri| mk_router_client(uri.clone(), tls.as_ref()))
             .collect::<Result<Vec<RouterClient>>>()?;
-        Ok(Self {
+        let default_router_uris = settings.routers.iter().map(|uri| uri.to_string()).collect();
+        let router = Self {
             keypair: settings.keypair.clone(),
             region: settings.region,
             uplinks,
             downlinks,
             validator,
+            validator_uri,
             routing_height: 0,
             clients: HashMap::new(),
             default_clients,
-        })
+            default_router_uris,
+            tls,
+            metrics,
+            routing_snapshot,
+            queue,
+        };
+        router.publish_routing_snapshot();
+        Ok(router)
     }
 
     pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
         let logger = logger.new(o!("module" => "router"));
         info!(logger, "starting router");
         let mut routing_stream = self.routing_stream().await?;
+        let mut drain_tick = tokio::time::interval(Duration::from_secs(QUEUE_DRAIN_INTERVAL_SECS));
+        // Set while a reconnect is in flight; the routing stream isn't polled until it resolves.
+        let mut reconnecting: Option<ReconnectHandle> = None;
         loop {
             tokio::select! {
                 _ = shutdown.clone() => {
                     info!(logger.clone(), "shutting down");
                     return Ok(())
                 },
-                routing = routing_stream.message() => match routing {
+                routing = routing_stream.message(), if reconnecting.is_none() => match routing {
                     Ok(Some(routing_response)) => self.handle_routing_update(logger.clone(), &routing_response),
                     Ok(None) => {
-                        info!(logger.clone(), "NO ROUTING RESPONSE?")
+                        info!(logger.clone(), "validator closed the routing stream, reconnecting");
+                        reconnecting = Some(self.spawn_reconnect(logger.clone()));
                     },
                     Err(err) => {
-                        //self.validator = mk_validator_client(self.validator.uri.clone())?;
-                        info!(logger.clone(), "ROUTING ERROR {:?}", err);
-                        panic!("ERROR {:?}", err)
+                        warn!(logger.clone(), "routing stream error, reconnecting: {:?}", err);
+                        reconnecting = Some(self.spawn_reconnect(logger.clone()));
+                    }
+                },
+                reconnected = async { reconnecting.as_mut().unwrap().await }, if reconnecting.is_some() => {
+                    reconnecting = None;
+                    match reconnected {
+                        Ok((client, stream)) => {
+                            info!(logger.clone(), "reconnected to validator");
+                            self.validator = client;
+                            routing_stream = stream;
+                        }
+                        Err(err) => warn!(logger.clone(), "reconnect task panicked: {:?}", err),
                     }
                 },
                 uplink = self.uplinks.recv() => match uplink {
@@ -102,10 +160,59 @@ impl Router {
                     },
                     None => debug!(logger, "ignoring closed downlinks channel"),
                 },
+                _ = drain_tick.tick() => self.drain_queue(logger.clone()).await,
             }
         }
     }
 
+    /// Retry every queued uplink against the current router clients of the
+    /// OUIs its `RouteTarget` names, or the default clients when none of those
+    /// OUIs is in the routing table; the first client that accepts it wins.
+    async fn drain_queue(&self, logger: Logger) {
+        self.queue
+            .drain(&logger, |target, message| {
+                let candidates = self.clients_for_target(&target);
+                async move {
+                    for mut client in candidates {
+                        if client.route(message.clone()).await.is_ok() {
+                            return true;
+                        }
+                    }
+                    false
+                }
+            })
+            .await;
+    }
+
+    fn clients_for_target(&self, target: &RouteTarget) -> Vec<RouterClient> {
+        match target {
+            RouteTarget::Ouis(ouis) => {
+                let found: Vec<RouterClient> = ouis
+                    .iter()
+                    .filter_map(|oui| self.clients.get(oui))
+                    .flat_map(|routing| routing.clients.clone())
+                    .collect();
+                if found.is_empty() {
+                    self.default_clients.clone()
+                } else {
+                    found
+                }
+            }
+            RouteTarget::Default => self.default_clients.clone(),
+        }
+    }
+
+    /// Spawn the reconnect-with-backoff loop as its own task so `run` keeps
+    /// polling its other branches instead of stalling on it.
+    fn spawn_reconnect(&self, logger: Logger) -> ReconnectHandle {
+        tokio::spawn(reconnect_loop(
+            self.validator_uri.clone(),
+            self.tls.clone(),
+            self.routing_height,
+            logger,
+        ))
+    }
+
     fn handle_routing_update(&mut self, logger: Logger, routing_response: &RoutingResponse) {
         if routing_response.height <= self.routing_height {
             warn!(
@@ -116,35 +223,64 @@ impl Router {
             )
         }
         for routing in &routing_response.routings {
-            match routing::Routing::from_proto(routing) {
-                Ok(client) => {
-                    self.clients.insert(routing.oui, client);
-                    ()
-                }
-                Err(err) => warn!(logger, "failed to construct router client: {:?}", err),
-            }
+            let client = routing::Routing::from_proto(routing, self.tls.as_ref());
+            self.clients.insert(routing.oui, client);
+        }
+        if routing_response.height > self.routing_height {
+            self.routing_height = routing_response.height;
         }
-        self.routing_height = routing_response.height;
         info!(
             logger,
             "updated routing to height {:?}", self.routing_height
-        )
+        );
+        self.publish_routing_snapshot();
+    }
+
+    /// Republish a `RoutingSnapshot` of the current routing tables.
+    fn publish_routing_snapshot(&self) {
+        let clients = self
+            .clients
+            .iter()
+            .map(|(oui, routing)| RouterClientInfo {
+                oui: *oui,
+                eui_filter_count: routing.filters.len(),
+                subnet_count: routing.subnets.len(),
+                router_uris: routing.uris.iter().map(|uri| uri.to_string()).collect(),
+            })
+            .collect();
+        let snapshot = RoutingSnapshot {
+            routing_height: self.routing_height,
+            clients,
+            default_router_uris: self.default_router_uris.clone(),
+        };
+        *self.routing_snapshot.write().unwrap() = snapshot;
     }
 
     async fn handle_uplink(&mut self, logger: Logger, uplink: LinkPacket) -> Result {
+        self.metrics.record_uplink_received();
         if uplink.packet.routing.is_none() {
             debug!(logger, "ignoring, no routing data");
             return Ok(());
         };
         let gateway_mac = uplink.gateway_mac;
         let message = uplink.to_state_channel_message(&self.keypair, self.region)?;
-        for mut client in self.router_clients_for_uplink(&uplink) {
+        let clients = self.router_clients_for_uplink(&uplink);
+        let target = route_target(&clients);
+        if clients.is_empty() {
+            debug!(logger, "no reachable router clients, queuing uplink for store-and-forward");
+            self.queue.enqueue(target, message, &logger).await;
+            return Ok(());
+        }
+        let mut route_handles = Vec::with_capacity(clients.len());
+        for (oui, mut client) in clients {
             let mut downlinks = self.downlinks.clone();
             let message = message.clone();
             let logger = logger.clone();
-            tokio::spawn(async move {
+            let metrics = self.metrics.clone();
+            route_handles.push(tokio::spawn(async move {
                 match client.route(message).await {
                     Ok(response) => {
+                        metrics.record_uplink_routed(oui);
                         if let Some(downlink) = LinkPacket::from_state_channel_message(
                             response.into_inner(),
                             gateway_mac,
@@ -156,27 +292,56 @@ impl Router {
                                 }
                             }
                         }
+                        true
+                    }
+                    Err(err) => {
+                        debug!(logger, "ignoring uplink error: {:?}", err);
+                        false
                     }
-                    Err(err) => debug!(logger, "ignoring uplink error: {:?}", err),
                 }
-            });
+            }));
         }
+        let queue = self.queue.clone();
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            let mut delivered = false;
+            for handle in route_handles {
+                if let Ok(true) = handle.await {
+                    delivered = true;
+                }
+            }
+            if !delivered {
+                queue.enqueue(target, message, &logger).await;
+            }
+        });
         Ok(())
     }
 
-    fn router_clients_for_uplink(&self, uplink: &LinkPacket) -> Vec<RouterClient> {
+    /// Resolve the router clients a given uplink should be sent to, each
+    /// tagged with the OUI it matched (`None` for the default clients).
+    fn router_clients_for_uplink(&self, uplink: &LinkPacket) -> Vec<(Option<u32>, RouterClient)> {
         match &uplink.packet.routing {
             Some(RoutingInformation {
                 data: Some(routing_data),
             }) => {
-                let found: Vec<RouterClient> = self
+                let found: Vec<(Option<u32>, RouterClient)> = self
                     .clients
-                    .values()
-                    .filter(|&routing| routing.matches_routing_data(&routing_data))
-                    .flat_map(|routing| routing.clients.clone())
+                    .iter()
+                    .filter(|(_, routing)| routing.matches_routing_data(&routing_data))
+                    .flat_map(|(oui, routing)| {
+                        routing
+                            .clients
+                            .iter()
+                            .cloned()
+                            .map(move |client| (Some(*oui), client))
+                    })
                     .collect();
                 if found.is_empty() {
-                    self.default_clients.clone()
+                    self.default_clients
+                        .iter()
+                        .cloned()
+                        .map(|client| (None, client))
+                        .collect()
                 } else {
                     found
                 }
@@ -185,12 +350,66 @@ impl Router {
         }
     }
 
+    /// Open a routing stream starting from the last known `routing_height`.
     async fn routing_stream(&mut self) -> Result<tonic::codec::Streaming<RoutingResponse>> {
         let stream = self
             .validator
-            .routing(RoutingRequest { height: 1 })
+            .routing(RoutingRequest {
+                height: self.routing_height.max(1),
+            })
             .await?
             .into_inner();
         Ok(stream)
     }
 }
+
+/// Derive the `RouteTarget` for a resolved client list.
+fn route_target(clients: &[(Option<u32>, RouterClient)]) -> RouteTarget {
+    let mut ouis: Vec<u32> = clients.iter().filter_map(|(oui, _)| *oui).collect();
+    if ouis.is_empty() {
+        return RouteTarget::Default;
+    }
+    ouis.sort_unstable();
+    ouis.dedup();
+    RouteTarget::Ouis(ouis)
+}
+
+type ReconnectHandle =
+    tokio::task::JoinHandle<(ValidatorClient, tonic::codec::Streaming<RoutingResponse>)>;
+
+/// Rebuild the validator client and re-open the routing stream, retrying
+/// with capped exponential backoff until it succeeds.
+async fn reconnect_loop(
+    validator_uri: Uri,
+    tls: Option<TlsSettings>,
+    routing_height: u64,
+    logger: Logger,
+) -> (ValidatorClient, tonic::codec::Streaming<RoutingResponse>) {
+    let mut backoff_secs = RECONNECT_BACKOFF_INITIAL_SECS;
+    loop {
+        match mk_validator_client(validator_uri.clone(), tls.as_ref()) {
+            Ok(mut client) => {
+                match client
+                    .routing(RoutingRequest {
+                        height: routing_height.max(1),
+                    })
+                    .await
+                {
+                    Ok(response) => return (client, response.into_inner()),
+                    Err(err) => warn!(logger, "failed to open routing stream: {:?}", err),
+                }
+            }
+            Err(err) => warn!(logger, "failed to rebuild validator client: {:?}", err),
+        }
+        tokio::time::sleep(backoff_with_jitter(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(RECONNECT_BACKOFF_MAX_SECS);
+    }
+}
+
+fn backoff_with_jitter(backoff_secs: u64) -> Duration {
+    let jitter_nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or(0);
+    Duration::from_secs(backoff_secs) + Duration::from_millis((jitter_nanos % 500) as u64)
+}
@@ -1,6 +1,10 @@
-use crate::router::{
-    filter::{DevAddrFilter, EuiFilter},
-    mk_router_client, RouterClient,
+use crate::{
+    router::{
+        filter::{DevAddrFilter, EuiFilter},
+        mk_router_client,
+        tls::TlsSettings,
+        RouterClient,
+    },
 };
 use helium_proto::routing_information::Data as RoutingData;
 use http::Uri;
@@ -10,6 +14,8 @@ pub struct Routing {
     pub(crate) filters: Vec<EuiFilter>,
     pub(crate) subnets: Vec<DevAddrFilter>,
     pub(crate) clients: Vec<RouterClient>,
+    /// URIs of the resolved router clients, in the same order as `clients`.
+    pub(crate) uris: Vec<Uri>,
 }
 
 impl Routing {
@@ -21,39 +27,39 @@ impl Routing {
             }
         }
     }
-}
 
-impl From<&helium_proto::Routing> for Routing {
-    fn from(r: &helium_proto::Routing) -> Self {
+    pub fn from_proto(r: &helium_proto::Routing, tls: Option<&TlsSettings>) -> Self {
         let filters = r.filters.iter().map(|f| EuiFilter::from_bin(&f)).collect();
         let subnets = r
             .subnets
             .iter()
             .map(|s| DevAddrFilter::from_bin(&s))
             .collect();
-        Self {
-            filters,
-            subnets,
-            clients: r
-                .addresses
-                .iter()
-                .filter_map(|address| match Uri::try_from(&address.uri[..]) {
-                    Ok(uri) => match mk_router_client(uri.clone()) {
-                        Ok(client) => {
-                            log::info!("made client for uri {:?}", uri);
-                            Some(client)
-                        }
-                        Err(err) => {
-                            log::warn!("failed to make client for uri {:?}: {:?}", uri, err);
-                            None
-                        }
-                    },
+        let (clients, uris): (Vec<RouterClient>, Vec<Uri>) = r
+            .addresses
+            .iter()
+            .filter_map(|address| match Uri::try_from(&address.uri[..]) {
+                Ok(uri) => match mk_router_client(uri.clone(), tls) {
+                    Ok(client) => {
+                        log::info!("made client for uri {:?}", uri);
+                        Some((client, uri))
+                    }
                     Err(err) => {
-                        log::warn!("invalid uri {:?}", err);
+                        log::warn!("failed to make client for uri {:?}: {:?}", uri, err);
                         None
                     }
-                })
-                .collect(),
+                },
+                Err(err) => {
+                    log::warn!("invalid uri {:?}", err);
+                    None
+                }
+            })
+            .unzip();
+        Self {
+            filters,
+            subnets,
+            clients,
+            uris,
         }
     }
 }
@@ -0,0 +1,39 @@
+use crate::error::Result;
+use http::Uri;
+use std::{fs, path::PathBuf};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub domain_name: Option<String>,
+}
+
+/// Whether `uri` asks for a secure channel (`https`/`grpcs`).
+pub fn uses_tls(uri: &Uri) -> bool {
+    matches!(uri.scheme_str(), Some("https") | Some("grpcs"))
+}
+
+pub fn client_tls_config(uri: &Uri, settings: Option<&TlsSettings>) -> Result<ClientTlsConfig> {
+    let mut config = ClientTlsConfig::new();
+    if let Some(settings) = settings {
+        if let Some(ca_path) = &settings.ca_cert {
+            let ca_pem = fs::read(ca_path)?;
+            config = config.ca_certificate(Certificate::from_pem(ca_pem));
+        }
+        if let (Some(cert_path), Some(key_path)) = (&settings.client_cert, &settings.client_key) {
+            let cert_pem = fs::read(cert_path)?;
+            let key_pem = fs::read(key_path)?;
+            config = config.identity(Identity::from_pem(cert_pem, key_pem));
+        }
+    }
+    let domain_name = settings
+        .and_then(|settings| settings.domain_name.clone())
+        .or_else(|| uri.host().map(str::to_string));
+    if let Some(domain_name) = domain_name {
+        config = config.domain_name(domain_name);
+    }
+    Ok(config)
+}
@@ -0,0 +1,224 @@
+use crate::error::{Error, Result};
+use helium_proto::BlockchainStateChannelMessageV1;
+use prost::Message as _;
+use slog::{debug, warn, Logger};
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+
+/// Store-and-forward settings: where queued uplinks are persisted, how many
+/// to keep, and their TTL.
+#[derive(Debug, Clone)]
+pub struct QueueSettings {
+    pub path: PathBuf,
+    pub max_entries: usize,
+    pub ttl: Duration,
+}
+
+/// The routing decision an uplink was queued with; retried against the same
+/// target on drain, rather than broadcast to every router.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteTarget {
+    Default,
+    Ouis(Vec<u32>),
+}
+
+struct Entry {
+    id: u64,
+    enqueued_at: u64,
+    target: RouteTarget,
+    message: BlockchainStateChannelMessageV1,
+}
+
+/// A bounded, on-disk queue of uplinks that couldn't be routed to any router.
+pub struct ForwardQueue {
+    settings: QueueSettings,
+    entries: Mutex<VecDeque<Entry>>,
+    next_id: AtomicU64,
+}
+
+pub type SharedForwardQueue = Arc<ForwardQueue>;
+
+impl ForwardQueue {
+    pub fn new(settings: QueueSettings, logger: &Logger) -> Result<SharedForwardQueue> {
+        std::fs::create_dir_all(&settings.path)?;
+        let mut entries = VecDeque::new();
+        let mut next_id = 0;
+        for dir_entry in std::fs::read_dir(&settings.path)? {
+            let dir_entry = dir_entry?;
+            let id = match dir_entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u64>().ok())
+            {
+                Some(id) => id,
+                None => continue,
+            };
+            let bytes = std::fs::read(dir_entry.path())?;
+            match decode_entry(&bytes) {
+                Ok((target, message)) => {
+                    let enqueued_at = dir_entry
+                        .metadata()
+                        .and_then(|meta| meta.modified())
+                        .map(unix_secs)
+                        .unwrap_or_else(|_| now_secs());
+                    entries.push_back(Entry {
+                        id,
+                        enqueued_at,
+                        target,
+                        message,
+                    });
+                    next_id = next_id.max(id + 1);
+                }
+                Err(err) => {
+                    warn!(logger, "dropping unreadable queued uplink {}: {:?}", id, err);
+                    let _ = std::fs::remove_file(dir_entry.path());
+                }
+            }
+        }
+        entries.make_contiguous().sort_by_key(|entry| entry.id);
+        Ok(Arc::new(Self {
+            settings,
+            entries: Mutex::new(entries),
+            next_id: AtomicU64::new(next_id),
+        }))
+    }
+
+    fn entry_path(&self, id: u64) -> PathBuf {
+        self.settings.path.join(id.to_string())
+    }
+
+    /// Persist `message` to disk and enqueue it, dropping the oldest entry
+    /// once `max_entries` is exceeded.
+    pub async fn enqueue(
+        &self,
+        target: RouteTarget,
+        message: BlockchainStateChannelMessageV1,
+        logger: &Logger,
+    ) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let bytes = match encode_entry(&target, &message) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!(logger, "failed to encode queued uplink: {:?}", err);
+                return;
+            }
+        };
+        if let Err(err) = std::fs::write(self.entry_path(id), bytes) {
+            warn!(logger, "failed to persist queued uplink: {:?}", err);
+            return;
+        }
+        let mut entries = self.entries.lock().await;
+        entries.push_back(Entry {
+            id,
+            enqueued_at: now_secs(),
+            target,
+            message,
+        });
+        while entries.len() > self.settings.max_entries {
+            if let Some(dropped) = entries.pop_front() {
+                debug!(logger, "dropped oldest queued uplink {}", dropped.id);
+                let _ = std::fs::remove_file(self.entry_path(dropped.id));
+            }
+        }
+    }
+
+    /// Try to resubmit every queued uplink via `route`, dropping entries that
+    /// have expired past `ttl` and entries `route` reports as delivered. The
+    /// queue lock isn't held across the `route` awaits.
+    pub async fn drain<F, Fut>(&self, logger: &Logger, mut route: F)
+    where
+        F: FnMut(RouteTarget, BlockchainStateChannelMessageV1) -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let pending = {
+            let mut entries = self.entries.lock().await;
+            std::mem::take(&mut *entries)
+        };
+        let now = now_secs();
+        let mut undelivered = VecDeque::new();
+        for entry in pending {
+            if now.saturating_sub(entry.enqueued_at) > self.settings.ttl.as_secs() {
+                debug!(logger, "dropping expired queued uplink {}", entry.id);
+                let _ = std::fs::remove_file(self.entry_path(entry.id));
+                continue;
+            }
+            if route(entry.target.clone(), entry.message.clone()).await {
+                let _ = std::fs::remove_file(self.entry_path(entry.id));
+            } else {
+                undelivered.push_back(entry);
+            }
+        }
+        if !undelivered.is_empty() {
+            let mut entries = self.entries.lock().await;
+            undelivered.append(&mut *entries);
+            *entries = undelivered;
+        }
+    }
+}
+
+const TARGET_TAG_DEFAULT: u8 = 0;
+const TARGET_TAG_OUIS: u8 = 1;
+
+fn encode_entry(target: &RouteTarget, message: &BlockchainStateChannelMessageV1) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    match target {
+        RouteTarget::Default => buf.push(TARGET_TAG_DEFAULT),
+        RouteTarget::Ouis(ouis) => {
+            buf.push(TARGET_TAG_OUIS);
+            buf.extend_from_slice(&(ouis.len() as u32).to_le_bytes());
+            for oui in ouis {
+                buf.extend_from_slice(&oui.to_le_bytes());
+            }
+        }
+    }
+    message.encode(&mut buf)?;
+    Ok(buf)
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<(RouteTarget, BlockchainStateChannelMessageV1)> {
+    let corrupt = || Error::ServerError("corrupt queue entry".to_string());
+    let (target, rest) = match bytes.first() {
+        Some(&TARGET_TAG_DEFAULT) => (RouteTarget::Default, &bytes[1..]),
+        Some(&TARGET_TAG_OUIS) => {
+            if bytes.len() < 5 {
+                return Err(corrupt());
+            }
+            let count = u32::from_le_bytes(bytes[1..5].try_into().unwrap()) as usize;
+            let mut offset = 5;
+            if offset + count * 4 > bytes.len() {
+                return Err(corrupt());
+            }
+            let mut ouis = Vec::with_capacity(count);
+            for _ in 0..count {
+                ouis.push(u32::from_le_bytes(
+                    bytes[offset..offset + 4].try_into().unwrap(),
+                ));
+                offset += 4;
+            }
+            (RouteTarget::Ouis(ouis), &bytes[offset..])
+        }
+        Some(_) => return Err(corrupt()),
+        None => return Err(corrupt()),
+    };
+    let message = BlockchainStateChannelMessageV1::decode(rest)?;
+    Ok((target, message))
+}
+
+fn now_secs() -> u64 {
+    unix_secs(SystemTime::now())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}